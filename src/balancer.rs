@@ -0,0 +1,91 @@
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use std::sync::{
+	atomic::{AtomicUsize, Ordering},
+	Arc,
+};
+
+#[derive(Debug, Clone, Copy, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BalancePolicy {
+	#[default]
+	RoundRobin,
+	Random,
+	LeastConnections,
+}
+
+/// Per-route load balancing state. Lives inside `DynamicConfig`, so it is
+/// shared across requests but reset whenever the config is reloaded.
+#[derive(Debug)]
+pub struct Balancer {
+	policy: BalancePolicy,
+	cursor: AtomicUsize,
+	in_flight: Arc<[AtomicUsize]>,
+}
+
+impl Balancer {
+	pub fn new(policy: BalancePolicy, len: usize) -> Self {
+		Self {
+			policy,
+			cursor: AtomicUsize::new(0),
+			in_flight: (0..len).map(|_| AtomicUsize::new(0)).collect(),
+		}
+	}
+
+	/// Returns the candidate indices in the order they should be tried.
+	pub fn order(&self, len: usize) -> Vec<usize> {
+		if len == 0 {
+			return Vec::new();
+		}
+
+		match self.policy {
+			BalancePolicy::RoundRobin => {
+				let start = self.cursor.fetch_add(1, Ordering::Relaxed) % len;
+				(0..len).map(|i| (start + i) % len).collect()
+			}
+			BalancePolicy::Random => {
+				let mut order: Vec<usize> = (0..len).collect();
+				order.shuffle(&mut rand::thread_rng());
+				order
+			}
+			BalancePolicy::LeastConnections => {
+				let mut order: Vec<usize> = (0..len).collect();
+				order.sort_by_key(|&index| self.in_flight[index].load(Ordering::Relaxed));
+				order
+			}
+		}
+	}
+
+	/// A cheaply cloneable handle to the in-flight counters, for use after the
+	/// winning candidate is known (typically past an `.await` point, once the
+	/// route itself is no longer borrowed).
+	pub fn in_flight(&self) -> InFlight {
+		InFlight(self.in_flight.clone())
+	}
+}
+
+#[derive(Clone)]
+pub struct InFlight(Arc<[AtomicUsize]>);
+
+impl InFlight {
+	pub fn acquire(&self, index: usize) -> ConnectionGuard {
+		self.0[index].fetch_add(1, Ordering::Relaxed);
+		ConnectionGuard {
+			in_flight: self.0.clone(),
+			index,
+		}
+	}
+}
+
+/// Tracks one in-flight request against a candidate; decrements the shared
+/// counter when the request (or its response) is done with the connection.
+pub struct ConnectionGuard {
+	in_flight: Arc<[AtomicUsize]>,
+	index: usize,
+}
+
+impl Drop for ConnectionGuard {
+	fn drop(&mut self) {
+		self.in_flight[self.index].fetch_sub(1, Ordering::Relaxed);
+	}
+}
@@ -0,0 +1,66 @@
+use crate::config::RouteConfig;
+
+use anyhow::{anyhow, Result};
+use std::{fs::File, io::BufReader, sync::Arc};
+use tokio_rustls::rustls::{
+	self,
+	server::{ClientHello, ResolvesServerCert},
+	sign::CertifiedKey,
+};
+use wildmatch::WildMatch;
+
+pub struct HostCertResolver {
+	default: Arc<CertifiedKey>,
+	hosts: Vec<(WildMatch, Arc<CertifiedKey>)>,
+}
+
+impl HostCertResolver {
+	pub fn load(cert_file: &str, key_file: &str, routes: &[RouteConfig]) -> Result<Self> {
+		let default = Arc::new(load_certified_key(cert_file, key_file)?);
+
+		let mut hosts = Vec::new();
+		for route in routes {
+			if let (Some(cert_file), Some(key_file)) = (&route.cert_file, &route.key_file) {
+				let certified_key = Arc::new(load_certified_key(cert_file, key_file)?);
+				hosts.push((route.host.clone(), certified_key));
+			}
+		}
+
+		Ok(Self { default, hosts })
+	}
+}
+
+impl ResolvesServerCert for HostCertResolver {
+	fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+		if let Some(server_name) = client_hello.server_name() {
+			if let Some((_, certified_key)) = self
+				.hosts
+				.iter()
+				.find(|(host, _)| host.matches(server_name))
+			{
+				return Some(certified_key.clone());
+			}
+		}
+
+		Some(self.default.clone())
+	}
+}
+
+fn load_certified_key(cert_file: &str, key_file: &str) -> Result<CertifiedKey> {
+	let cert_fh = File::open(cert_file)
+		.map_err(|e| anyhow!("Failed to open cert file {}: {}", cert_file, e))?;
+	let key_fh =
+		File::open(key_file).map_err(|e| anyhow!("Failed to open key file {}: {}", key_file, e))?;
+
+	let certs = rustls_pemfile::certs(&mut BufReader::new(cert_fh))
+		.collect::<Result<_, _>>()
+		.map_err(|e| anyhow!("Failed to load cert file {}: {}", cert_file, e))?;
+	let key = rustls_pemfile::private_key(&mut BufReader::new(key_fh))
+		.map_err(|e| anyhow!("Failed to load key file {}: {}", key_file, e))?
+		.ok_or_else(|| anyhow!("Missing key in key file {}", key_file))?;
+
+	let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
+		.map_err(|e| anyhow!("Failed to load signing key from {}: {}", key_file, e))?;
+
+	Ok(CertifiedKey::new(certs, signing_key))
+}
@@ -1,10 +1,16 @@
+use crate::{
+	balancer::{BalancePolicy, Balancer},
+	cert_resolver::HostCertResolver,
+	endpoint::Endpoint,
+	proxy_protocol::ProxyProtocolVersion,
+};
+
 use anyhow::{anyhow, Error, Result};
 use serde::Deserialize;
 use std::{
-	env,
-	fs::{self, File},
-	io::BufReader,
-	net::SocketAddr,
+	env, fs,
+	net::{IpAddr, Ipv6Addr, SocketAddr},
+	sync::Arc,
 };
 use tokio_rustls::rustls;
 use wildmatch::WildMatch;
@@ -12,26 +18,50 @@ use wildmatch::WildMatch;
 #[derive(Debug)]
 pub struct StaticConfig {
 	pub config_file: String,
-	pub http_port: u16,
-	pub https_port: u16,
+	pub http_bind: Endpoint,
+	pub https_bind: Endpoint,
+	/// Only used when a listener binds to the unspecified (`::`) IPv6 address;
+	/// restricts it to IPv6-only instead of also accepting IPv4-mapped connections.
+	pub ipv6_only: bool,
 }
 
 impl StaticConfig {
 	pub fn load() -> Result<Self> {
+		let http_port = env::var("HTTP_PORT").map_or(Ok(8080), |p| {
+			p.parse::<u16>()
+				.map_err(|e| anyhow!("HTTP_PORT must be a valid port: {}", e))
+		})?;
+		let https_port = env::var("HTTPS_PORT").map_or(Ok(8443), |p| {
+			p.parse::<u16>()
+				.map_err(|e| anyhow!("HTTPS_PORT must be a valid port: {}", e))
+		})?;
+		let bind_address = env::var("BIND_ADDRESS").map_or(Ok(IpAddr::V6(Ipv6Addr::UNSPECIFIED)), |a| {
+			a.parse::<IpAddr>()
+				.map_err(|e| anyhow!("BIND_ADDRESS must be a valid IP address: {}", e))
+		})?;
+		let ipv6_only = env::var("IPV6_ONLY").map_or(Ok(false), |v| {
+			v.parse::<bool>()
+				.map_err(|e| anyhow!("IPV6_ONLY must be true or false: {}", e))
+		})?;
+
 		Ok(Self {
 			config_file: env::var("CONFIG_FILE").unwrap_or("config.toml".into()),
-			http_port: env::var("HTTP_PORT").map_or(Ok(8080), |p| {
-				p.parse::<u16>()
-					.map_err(|e| anyhow!("HTTP_PORT must be a valid port: {}", e))
-			})?,
-			https_port: env::var("HTTPS_PORT").map_or(Ok(8443), |p| {
-				p.parse::<u16>()
-					.map_err(|e| anyhow!("HTTPS_PORT must be a valid port: {}", e))
-			})?,
+			http_bind: bind_endpoint("HTTP_BIND", bind_address, http_port)?,
+			https_bind: bind_endpoint("HTTPS_BIND", bind_address, https_port)?,
+			ipv6_only,
 		})
 	}
 }
 
+fn bind_endpoint(env_var: &str, default_address: IpAddr, default_port: u16) -> Result<Endpoint> {
+	match env::var(env_var) {
+		Ok(value) => {
+			Endpoint::parse(&value).map_err(|e| anyhow!("{} must be a valid endpoint: {}", env_var, e))
+		}
+		Err(_) => Ok(Endpoint::Tcp(SocketAddr::new(default_address, default_port))),
+	}
+}
+
 #[derive(Debug, Deserialize)]
 pub struct DynamicConfig {
 	pub cert_file: String,
@@ -63,25 +93,48 @@ impl DynamicConfig {
 #[serde(try_from = "RouteConfigUnchecked")]
 pub struct RouteConfig {
 	pub host: WildMatch,
-	pub address: SocketAddr,
+	pub addresses: Vec<Endpoint>,
+	pub balancer: Balancer,
+	pub proxy_protocol: Option<ProxyProtocolVersion>,
+	pub cert_file: Option<String>,
+	pub key_file: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct RouteConfigUnchecked {
 	host: String,
-	address: String,
+	addresses: Vec<String>,
+	#[serde(default)]
+	balance: BalancePolicy,
+	#[serde(default)]
+	proxy_protocol: Option<ProxyProtocolVersion>,
+	#[serde(default)]
+	cert_file: Option<String>,
+	#[serde(default)]
+	key_file: Option<String>,
 }
 
 impl TryFrom<RouteConfigUnchecked> for RouteConfig {
 	type Error = Error;
 
 	fn try_from(config: RouteConfigUnchecked) -> Result<Self, Self::Error> {
+		if config.addresses.is_empty() {
+			return Err(anyhow!("Route for {} must have at least one address", config.host));
+		}
+
+		let addresses = config
+			.addresses
+			.iter()
+			.map(|address| Endpoint::parse(address))
+			.collect::<Result<Vec<_>>>()?;
+
 		Ok(Self {
 			host: WildMatch::new(&config.host),
-			address: config
-				.address
-				.parse::<SocketAddr>()
-				.map_err(|e| anyhow!("Failed to parse address {}: {}", config.address, e))?,
+			balancer: Balancer::new(config.balance, addresses.len()),
+			addresses,
+			proxy_protocol: config.proxy_protocol,
+			cert_file: config.cert_file,
+			key_file: config.key_file,
 		})
 	}
 }
@@ -92,33 +145,15 @@ pub struct ServerConfig {
 
 impl ServerConfig {
 	pub fn load(dynamic_config: &DynamicConfig) -> Result<ServerConfig> {
-		let cert_file = File::open(&dynamic_config.cert_file).map_err(|e| {
-			anyhow!(
-				"Failed to open cert file {}: {}",
-				dynamic_config.cert_file,
-				e
-			)
-		})?;
-		let key_file = File::open(&dynamic_config.key_file)
-			.map_err(|e| anyhow!("Failed to open key file {}: {}", dynamic_config.key_file, e))?;
-
-		let certs = rustls_pemfile::certs(&mut BufReader::new(cert_file))
-			.collect::<Result<_, _>>()
-			.map_err(|e| {
-				anyhow!(
-					"Failed to load cert file {}: {}",
-					dynamic_config.cert_file,
-					e
-				)
-			})?;
-		let key = rustls_pemfile::private_key(&mut BufReader::new(key_file))
-			.map_err(|e| anyhow!("Failed to load key file {}: {}", dynamic_config.key_file, e))?
-			.ok_or_else(|| anyhow!("Missing key in key file {}", dynamic_config.key_file))?;
+		let cert_resolver = HostCertResolver::load(
+			&dynamic_config.cert_file,
+			&dynamic_config.key_file,
+			&dynamic_config.routes,
+		)?;
 
 		let mut config = rustls::ServerConfig::builder()
 			.with_no_client_auth()
-			.with_single_cert(certs, key)
-			.map_err(|e| anyhow!("Failed to create server config: {}", e))?;
+			.with_cert_resolver(Arc::new(cert_resolver));
 
 		config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec(), b"http/1.0".to_vec()];
 
@@ -0,0 +1,183 @@
+use crate::resolver::DnsResolver;
+
+use anyhow::{anyhow, Result};
+use pin_project::pin_project;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+	io,
+	net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+	path::PathBuf,
+	pin::Pin,
+	task::{Context, Poll},
+};
+use tokio::{
+	io::{AsyncRead, AsyncWrite, ReadBuf},
+	net::{TcpListener, TcpStream, UnixListener, UnixStream},
+};
+
+/// An upstream or listener bind target: a `SocketAddr`, `unix:<path>`, or an
+/// unresolved `host:port` name looked up through a [`DnsResolver`] at connect time.
+#[derive(Debug, Clone)]
+pub enum Endpoint {
+	Tcp(SocketAddr),
+	Unix(PathBuf),
+	Named { host: String, port: u16 },
+}
+
+impl Endpoint {
+	pub fn parse(value: &str) -> Result<Self> {
+		if let Some(path) = value.strip_prefix("unix:") {
+			return Ok(Self::Unix(PathBuf::from(path)));
+		}
+
+		if let Ok(addr) = value.parse::<SocketAddr>() {
+			return Ok(Self::Tcp(addr));
+		}
+
+		let (host, port) = value
+			.rsplit_once(':')
+			.ok_or_else(|| anyhow!("Failed to parse address {}: expected host:port", value))?;
+		let port = port
+			.parse::<u16>()
+			.map_err(|e| anyhow!("Failed to parse port in address {}: {}", value, e))?;
+
+		Ok(Self::Named {
+			host: host.to_string(),
+			port,
+		})
+	}
+
+	pub async fn connect(&self, resolver: &DnsResolver) -> Result<EndpointStream> {
+		match self {
+			Self::Tcp(addr) => Ok(EndpointStream::Tcp(TcpStream::connect(addr).await?)),
+			Self::Unix(path) => Ok(EndpointStream::Unix(UnixStream::connect(path).await?)),
+			Self::Named { host, port } => {
+				let addrs = resolver.resolve(host).await?;
+
+				let mut last_err = None;
+				for ip in addrs {
+					match TcpStream::connect(SocketAddr::new(ip, *port)).await {
+						Ok(stream) => return Ok(EndpointStream::Tcp(stream)),
+						Err(e) => last_err = Some(e),
+					}
+				}
+
+				Err(
+					last_err.map_or_else(|| anyhow!("No addresses found for {}", host), Into::into),
+				)
+			}
+		}
+	}
+}
+
+/// A listener bound to either a TCP address or a Unix domain socket.
+pub enum Listener {
+	Tcp(TcpListener),
+	Unix(UnixListener),
+}
+
+impl Listener {
+	pub async fn bind(endpoint: &Endpoint, ipv6_only: bool) -> io::Result<Self> {
+		match endpoint {
+			Endpoint::Tcp(addr @ SocketAddr::V6(v6)) if v6.ip().is_unspecified() => {
+				Ok(Self::Tcp(bind_dual_stack(*addr, ipv6_only)?))
+			}
+			Endpoint::Tcp(addr) => Ok(Self::Tcp(TcpListener::bind(addr).await?)),
+			Endpoint::Named { host, port } => {
+				Ok(Self::Tcp(TcpListener::bind((host.as_str(), *port)).await?))
+			}
+			Endpoint::Unix(path) => {
+				// Reloading after a crash would otherwise fail with "address in use".
+				let _ = std::fs::remove_file(path);
+				Ok(Self::Unix(UnixListener::bind(path)?))
+			}
+		}
+	}
+
+	pub async fn accept(&self) -> io::Result<(EndpointStream, SocketAddr)> {
+		match self {
+			Self::Tcp(listener) => {
+				let (stream, addr) = listener.accept().await?;
+				Ok((EndpointStream::Tcp(stream), addr))
+			}
+			Self::Unix(listener) => {
+				let (stream, _) = listener.accept().await?;
+				Ok((EndpointStream::Unix(stream), unspecified_addr()))
+			}
+		}
+	}
+}
+
+fn unspecified_addr() -> SocketAddr {
+	SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))
+}
+
+/// Binds an IPv6 socket with `IPV6_V6ONLY` disabled (unless `ipv6_only` is
+/// set), so a single listener accepts both IPv6 and IPv4-mapped clients.
+fn bind_dual_stack(addr: SocketAddr, ipv6_only: bool) -> io::Result<TcpListener> {
+	let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+	socket.set_only_v6(ipv6_only)?;
+	socket.set_reuse_address(true)?;
+	socket.set_nonblocking(true)?;
+	socket.bind(&addr.into())?;
+	socket.listen(1024)?;
+
+	TcpListener::from_std(socket.into())
+}
+
+/// A connected stream to either side of an `Endpoint`, unified so callers
+/// (HTTP handshake, PROXY protocol header) don't need to care which one.
+#[pin_project(project = EndpointStreamProj)]
+pub enum EndpointStream {
+	Tcp(#[pin] TcpStream),
+	Unix(#[pin] UnixStream),
+}
+
+impl EndpointStream {
+	pub fn local_addr(&self) -> io::Result<SocketAddr> {
+		match self {
+			Self::Tcp(stream) => stream.local_addr(),
+			Self::Unix(_) => Ok(unspecified_addr()),
+		}
+	}
+}
+
+impl AsyncRead for EndpointStream {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		match self.project() {
+			EndpointStreamProj::Tcp(stream) => stream.poll_read(cx, buf),
+			EndpointStreamProj::Unix(stream) => stream.poll_read(cx, buf),
+		}
+	}
+}
+
+impl AsyncWrite for EndpointStream {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		match self.project() {
+			EndpointStreamProj::Tcp(stream) => stream.poll_write(cx, buf),
+			EndpointStreamProj::Unix(stream) => stream.poll_write(cx, buf),
+		}
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		match self.project() {
+			EndpointStreamProj::Tcp(stream) => stream.poll_flush(cx),
+			EndpointStreamProj::Unix(stream) => stream.poll_flush(cx),
+		}
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		match self.project() {
+			EndpointStreamProj::Tcp(stream) => stream.poll_shutdown(cx),
+			EndpointStreamProj::Unix(stream) => stream.poll_shutdown(cx),
+		}
+	}
+}
@@ -1,5 +1,10 @@
+mod balancer;
+mod cert_resolver;
 mod config;
+mod endpoint;
 mod proxy;
+mod proxy_protocol;
+mod resolver;
 mod server;
 
 fn main() {
@@ -1,6 +1,12 @@
-use crate::config::DynamicConfig;
+use crate::{
+	balancer::ConnectionGuard,
+	config::{DynamicConfig, RouteConfig},
+	endpoint::{Endpoint, EndpointStream},
+	proxy_protocol::{self, ProxyProtocolVersion},
+	resolver::DnsResolver,
+};
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 use core::task::{Context, Poll};
 use http_body_util::{Empty, Full};
 use hyper::{
@@ -11,7 +17,10 @@ use hyper::{
 	service::Service,
 	HeaderMap, Request, Response, StatusCode, Uri, Version,
 };
-use hyper_util::rt::TokioIo;
+use hyper_util::{
+	client::legacy::{connect::HttpConnector, Client},
+	rt::TokioIo,
+};
 use itertools::Itertools;
 use pin_project::pin_project;
 use std::{
@@ -20,25 +29,49 @@ use std::{
 	pin::Pin,
 	sync::{Arc, RwLock},
 };
-use tokio::{net::TcpStream, try_join};
+use tokio::try_join;
 
 pub struct Proxy {
 	secure: bool,
 	dynamic_config: Arc<RwLock<DynamicConfig>>,
+	client: Client<HttpConnector, Incoming>,
+	resolver: Arc<DnsResolver>,
+	client_addr: SocketAddr,
+	server_addr: SocketAddr,
 }
 
 impl Proxy {
-	pub fn new_secure(dynamic_config: Arc<RwLock<DynamicConfig>>) -> Self {
+	pub fn new_secure(
+		client: Client<HttpConnector, Incoming>,
+		dynamic_config: Arc<RwLock<DynamicConfig>>,
+		resolver: Arc<DnsResolver>,
+		client_addr: SocketAddr,
+		server_addr: SocketAddr,
+	) -> Self {
 		Self {
+			client,
 			dynamic_config,
+			resolver,
 			secure: true,
+			client_addr,
+			server_addr,
 		}
 	}
 
-	pub fn new_unsecure(dynamic_config: Arc<RwLock<DynamicConfig>>) -> Self {
+	pub fn new_unsecure(
+		client: Client<HttpConnector, Incoming>,
+		dynamic_config: Arc<RwLock<DynamicConfig>>,
+		resolver: Arc<DnsResolver>,
+		client_addr: SocketAddr,
+		server_addr: SocketAddr,
+	) -> Self {
 		Self {
+			client,
 			dynamic_config,
+			resolver,
 			secure: false,
+			client_addr,
+			server_addr,
 		}
 	}
 
@@ -94,13 +127,9 @@ impl Proxy {
 		}()));
 	}
 
-	fn forward(&self, in_req: Request<Incoming>, host: &str, address: &SocketAddr) -> ProxyFuture {
+	fn forward(&self, in_req: Request<Incoming>, host: &str, route: &RouteConfig) -> ProxyFuture {
 		let mut out_req = in_req;
 		*out_req.version_mut() = Version::HTTP_11;
-		*out_req.uri_mut() = Uri::builder()
-			.path_and_query(out_req.uri().path_and_query().unwrap().clone())
-			.build()
-			.unwrap();
 
 		out_req.headers_mut().remove("Keep-Alive");
 		out_req.headers_mut().remove("Connection");
@@ -112,21 +141,72 @@ impl Proxy {
 
 		self.merge_cookie_headers(out_req.headers_mut());
 
-		let address = *address;
+		// The pooled client dials a fixed authority per request and can't be
+		// steered to a different candidate mid-request, so it only ever covers
+		// the single-upstream case. Routes with more than one address deliberately
+		// skip the pool and take the dedicated per-request handshake below instead,
+		// since that's what makes load-balanced failover and PROXY protocol
+		// emission possible (picking a candidate, retrying the next on connect
+		// failure, writing the header before the HTTP handshake) - don't "fix"
+		// this by funneling load-balanced routes through the pooled client.
+		if let ([Endpoint::Tcp(address)], None) = (route.addresses.as_slice(), route.proxy_protocol) {
+			*out_req.uri_mut() = Uri::builder()
+				.scheme("http")
+				.authority(address.to_string())
+				.path_and_query(out_req.uri().path_and_query().unwrap().clone())
+				.build()
+				.unwrap();
+
+			let client = self.client.clone();
+			return ProxyFuture::Boxed(Box::pin(async move {
+				let in_res = client.request(out_req).await?;
+				let out_res = in_res.map(ProxyBody::Incoming);
+
+				Ok(out_res)
+			}));
+		}
+
+		*out_req.uri_mut() = Uri::builder()
+			.path_and_query(out_req.uri().path_and_query().unwrap().clone())
+			.build()
+			.unwrap();
+
+		let addresses = route.addresses.clone();
+		let order = route.balancer.order(addresses.len());
+		let in_flight = route.balancer.in_flight();
+		let proxy_protocol = route.proxy_protocol;
+		let resolver = self.resolver.clone();
+		let client_addr = self.client_addr;
+		let server_addr = self.server_addr;
 		return ProxyFuture::Boxed(Box::pin(async move {
-			let stream = TcpStream::connect(address).await?;
+			let (index, stream) = connect_upstream(
+				&addresses,
+				&order,
+				proxy_protocol,
+				&resolver,
+				client_addr,
+				server_addr,
+			)
+			.await?;
+			let guard = in_flight.acquire(index);
+
 			let (mut sender, conn) = http1::handshake(TokioIo::new(stream)).await?;
 
 			tokio::spawn(conn);
 
 			let in_res = sender.send_request(out_req).await?;
-			let out_res = in_res.map(ProxyBody::Incoming);
+			let out_res = in_res.map(|body| {
+				ProxyBody::Counted(CountedBody {
+					inner: body,
+					_guard: guard,
+				})
+			});
 
 			Ok(out_res)
 		}));
 	}
 
-	fn upgrade(&self, in_req: Request<Incoming>, host: &str, address: &SocketAddr) -> ProxyFuture {
+	fn upgrade(&self, in_req: Request<Incoming>, host: &str, route: &RouteConfig) -> ProxyFuture {
 		let mut out_req = Request::new(Empty::<Bytes>::new());
 		out_req.headers_mut().clone_from(in_req.headers());
 		out_req.method_mut().clone_from(in_req.method());
@@ -148,9 +228,25 @@ impl Proxy {
 
 		self.merge_cookie_headers(out_req.headers_mut());
 
-		let address = *address;
+		let addresses = route.addresses.clone();
+		let order = route.balancer.order(addresses.len());
+		let in_flight = route.balancer.in_flight();
+		let proxy_protocol = route.proxy_protocol;
+		let resolver = self.resolver.clone();
+		let client_addr = self.client_addr;
+		let server_addr = self.server_addr;
 		return ProxyFuture::Boxed(Box::pin(async move {
-			let stream = TcpStream::connect(address).await?;
+			let (index, stream) = connect_upstream(
+				&addresses,
+				&order,
+				proxy_protocol,
+				&resolver,
+				client_addr,
+				server_addr,
+			)
+			.await?;
+			let guard = in_flight.acquire(index);
+
 			let (mut sender, conn) = http1::handshake(TokioIo::new(stream)).await?;
 
 			tokio::spawn(conn.with_upgrades());
@@ -163,6 +259,10 @@ impl Proxy {
 			*res_out.status_mut() = in_res.status();
 
 			tokio::spawn(async move {
+				// Held for the lifetime of the tunnel, not just the handshake,
+				// so `least_connections` sees the connection as busy the whole time.
+				let _guard = guard;
+
 				let (res_upgraded, req_upgraded) =
 					try_join!(hyper::upgrade::on(in_res), hyper::upgrade::on(in_req))?;
 
@@ -179,6 +279,40 @@ impl Proxy {
 	}
 }
 
+/// Tries each candidate address in `order`, in turn, returning the first one
+/// that connects (and, if configured, accepts the PROXY protocol header).
+/// Falls through to the next candidate on failure instead of giving up.
+async fn connect_upstream(
+	addresses: &[Endpoint],
+	order: &[usize],
+	proxy_protocol: Option<ProxyProtocolVersion>,
+	resolver: &DnsResolver,
+	client_addr: SocketAddr,
+	server_addr: SocketAddr,
+) -> Result<(usize, EndpointStream)> {
+	let mut last_err = None;
+
+	for &index in order {
+		let attempt: Result<EndpointStream> = async {
+			let mut stream = addresses[index].connect(resolver).await?;
+
+			if let Some(version) = proxy_protocol {
+				proxy_protocol::write(&mut stream, version, client_addr, server_addr).await?;
+			}
+
+			Ok(stream)
+		}
+		.await;
+
+		match attempt {
+			Ok(stream) => return Ok((index, stream)),
+			Err(e) => last_err = Some(e),
+		}
+	}
+
+	Err(last_err.unwrap_or_else(|| anyhow!("Route has no upstream addresses configured")))
+}
+
 impl Service<Request<Incoming>> for Proxy {
 	type Response = Response<ProxyBody>;
 	type Error = Error;
@@ -213,10 +347,8 @@ impl Service<Request<Incoming>> for Proxy {
 					.into_iter()
 					.all_equal_value()
 				{
-					Ok(value) if value == "websocket" => {
-						self.upgrade(req, host.as_str(), &route.address)
-					}
-					_ => self.forward(req, host.as_str(), &route.address),
+					Ok(value) if value == "websocket" => self.upgrade(req, host.as_str(), route),
+					_ => self.forward(req, host.as_str(), route),
 				};
 			}
 		}
@@ -230,6 +362,7 @@ pub enum ProxyBody {
 	Full(#[pin] Full<Bytes>),
 	Empty(#[pin] Empty<Bytes>),
 	Incoming(#[pin] Incoming),
+	Counted(#[pin] CountedBody),
 }
 
 impl Body for ProxyBody {
@@ -244,6 +377,7 @@ impl Body for ProxyBody {
 			ProxyBodyProj::Full(full) => full.poll_frame(cx).map_err(|e| e.into()),
 			ProxyBodyProj::Empty(empty) => empty.poll_frame(cx).map_err(|e| e.into()),
 			ProxyBodyProj::Incoming(incoming) => incoming.poll_frame(cx).map_err(|e| e.into()),
+			ProxyBodyProj::Counted(counted) => counted.poll_frame(cx),
 		}
 	}
 
@@ -252,6 +386,7 @@ impl Body for ProxyBody {
 			ProxyBody::Full(full) => full.is_end_stream(),
 			ProxyBody::Empty(empty) => empty.is_end_stream(),
 			ProxyBody::Incoming(incoming) => incoming.is_end_stream(),
+			ProxyBody::Counted(counted) => counted.is_end_stream(),
 		}
 	}
 
@@ -260,10 +395,41 @@ impl Body for ProxyBody {
 			ProxyBody::Full(full) => full.size_hint(),
 			ProxyBody::Empty(empty) => empty.size_hint(),
 			ProxyBody::Incoming(incoming) => incoming.size_hint(),
+			ProxyBody::Counted(counted) => counted.size_hint(),
 		}
 	}
 }
 
+/// Wraps an upstream body together with its load-balancer [`ConnectionGuard`],
+/// so the in-flight counter only drops once the response body (not just its
+/// headers) has been fully read or dropped.
+#[pin_project]
+pub struct CountedBody {
+	#[pin]
+	inner: Incoming,
+	_guard: ConnectionGuard,
+}
+
+impl Body for CountedBody {
+	type Data = Bytes;
+	type Error = Error;
+
+	fn poll_frame(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+	) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+		self.project().inner.poll_frame(cx).map_err(|e| e.into())
+	}
+
+	fn is_end_stream(&self) -> bool {
+		self.inner.is_end_stream()
+	}
+
+	fn size_hint(&self) -> SizeHint {
+		self.inner.size_hint()
+	}
+}
+
 #[pin_project(project = ProxyFutureProj)]
 pub enum ProxyFuture {
 	Boxed(#[pin] Pin<Box<dyn Future<Output = Result<Response<ProxyBody>>> + Send + Sync>>),
@@ -0,0 +1,82 @@
+use serde::Deserialize;
+use std::{io, net::SocketAddr};
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyProtocolVersion {
+	V1,
+	V2,
+}
+
+const V2_SIGNATURE: [u8; 12] = [
+	0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+
+pub async fn write<W: AsyncWriteExt + Unpin>(
+	writer: &mut W,
+	version: ProxyProtocolVersion,
+	client_addr: SocketAddr,
+	server_addr: SocketAddr,
+) -> io::Result<()> {
+	let header = match version {
+		ProxyProtocolVersion::V1 => encode_v1(client_addr, server_addr),
+		ProxyProtocolVersion::V2 => encode_v2(client_addr, server_addr),
+	};
+
+	writer.write_all(&header).await
+}
+
+fn encode_v1(client_addr: SocketAddr, server_addr: SocketAddr) -> Vec<u8> {
+	let protocol = match (client_addr, server_addr) {
+		(SocketAddr::V4(_), SocketAddr::V4(_)) => "TCP4",
+		_ => "TCP6",
+	};
+
+	format!(
+		"PROXY {} {} {} {} {}\r\n",
+		protocol,
+		client_addr.ip(),
+		server_addr.ip(),
+		client_addr.port(),
+		server_addr.port()
+	)
+	.into_bytes()
+}
+
+fn encode_v2(client_addr: SocketAddr, server_addr: SocketAddr) -> Vec<u8> {
+	let mut header = Vec::with_capacity(28);
+
+	header.extend_from_slice(&V2_SIGNATURE);
+	header.push(0x21);
+
+	match (client_addr, server_addr) {
+		(SocketAddr::V4(client), SocketAddr::V4(server)) => {
+			header.push(0x11);
+			header.extend_from_slice(&12u16.to_be_bytes());
+			header.extend_from_slice(&client.ip().octets());
+			header.extend_from_slice(&server.ip().octets());
+			header.extend_from_slice(&client.port().to_be_bytes());
+			header.extend_from_slice(&server.port().to_be_bytes());
+		}
+		(client, server) => {
+			let client_ip = match client.ip() {
+				std::net::IpAddr::V6(ip) => ip,
+				std::net::IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+			};
+			let server_ip = match server.ip() {
+				std::net::IpAddr::V6(ip) => ip,
+				std::net::IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+			};
+
+			header.push(0x21);
+			header.extend_from_slice(&36u16.to_be_bytes());
+			header.extend_from_slice(&client_ip.octets());
+			header.extend_from_slice(&server_ip.octets());
+			header.extend_from_slice(&client.port().to_be_bytes());
+			header.extend_from_slice(&server.port().to_be_bytes());
+		}
+	}
+
+	header
+}
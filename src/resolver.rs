@@ -0,0 +1,75 @@
+use anyhow::{anyhow, Result};
+use hickory_resolver::TokioAsyncResolver;
+use lru::LruCache;
+use std::{net::IpAddr, num::NonZeroUsize, sync::Mutex, time::Instant};
+
+const CACHE_CAPACITY: usize = 4096;
+
+struct CacheEntry {
+	addrs: Vec<IpAddr>,
+	expires_at: Instant,
+}
+
+/// Resolves `host:port` upstreams through an async DNS resolver, caching
+/// results up to their record TTL so every request doesn't pay for a lookup.
+pub struct DnsResolver {
+	resolver: TokioAsyncResolver,
+	cache: Mutex<LruCache<String, CacheEntry>>,
+}
+
+impl DnsResolver {
+	/// Picks up the system's own nameservers, search domains and `/etc/hosts`
+	/// (via `/etc/resolv.conf` or the platform equivalent) instead of a
+	/// hardcoded public resolver, so internal names (k8s/CoreDNS, Consul, ...)
+	/// resolve the same way they would for any other process on the host.
+	pub fn new() -> Result<Self> {
+		Ok(Self {
+			resolver: TokioAsyncResolver::tokio_from_system_conf()
+				.map_err(|e| anyhow!("Failed to load system DNS configuration: {}", e))?,
+			cache: Mutex::new(LruCache::new(NonZeroUsize::new(CACHE_CAPACITY).unwrap())),
+		})
+	}
+
+	/// Returns every address currently on record for `host`, in resolver
+	/// order, so callers can fail over between them on connect.
+	pub async fn resolve(&self, host: &str) -> Result<Vec<IpAddr>> {
+		if let Some(addrs) = self.cached(host) {
+			return Ok(addrs);
+		}
+
+		let lookup = self
+			.resolver
+			.lookup_ip(host)
+			.await
+			.map_err(|e| anyhow!("Failed to resolve {}: {}", host, e))?;
+
+		let expires_at = lookup.as_lookup().valid_until();
+		let addrs = lookup.into_iter().collect::<Vec<_>>();
+
+		if addrs.is_empty() {
+			return Err(anyhow!("No addresses found for {}", host));
+		}
+
+		self.cache.lock().unwrap().put(
+			host.to_string(),
+			CacheEntry {
+				addrs: addrs.clone(),
+				expires_at,
+			},
+		);
+
+		Ok(addrs)
+	}
+
+	fn cached(&self, host: &str) -> Option<Vec<IpAddr>> {
+		let mut cache = self.cache.lock().unwrap();
+		let entry = cache.get(host)?;
+
+		if entry.expires_at <= Instant::now() {
+			cache.pop(host);
+			return None;
+		}
+
+		Some(entry.addrs.clone())
+	}
+}
@@ -1,6 +1,8 @@
 use crate::{
 	config::{DynamicConfig, ServerConfig, StaticConfig},
+	endpoint::Listener,
 	proxy::Proxy,
+	resolver::DnsResolver,
 };
 
 use anyhow::{anyhow, Result};
@@ -10,11 +12,7 @@ use hyper_util::{
 	rt::{TokioExecutor, TokioIo},
 	server::conn::auto,
 };
-use std::{
-	net::{Ipv4Addr, SocketAddrV4},
-	sync::{Arc, RwLock},
-};
-use tokio::net::TcpListener;
+use std::sync::{Arc, RwLock};
 
 #[cfg(unix)]
 use tokio::signal::unix::SignalKind;
@@ -29,22 +27,21 @@ pub async fn run() -> Result<()> {
 		&dynamic_config.read().unwrap(),
 	)?));
 
-	let http_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, static_config.http_port);
-	let https_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, static_config.https_port);
-
-	let http_listener = TcpListener::bind(http_addr)
+	let http_listener = Listener::bind(&static_config.http_bind, static_config.ipv6_only)
 		.await
-		.map_err(|e| anyhow!("Failed to bind address {}: {}", http_addr, e))?;
-	let https_listener = TcpListener::bind(https_addr)
+		.map_err(|e| anyhow!("Failed to bind {:?}: {}", static_config.http_bind, e))?;
+	let https_listener = Listener::bind(&static_config.https_bind, static_config.ipv6_only)
 		.await
-		.map_err(|e| anyhow!("Failed to bind address {}: {}", https_addr, e))?;
+		.map_err(|e| anyhow!("Failed to bind {:?}: {}", static_config.https_bind, e))?;
 
 	let client = Client::builder(TokioExecutor::new()).build_http();
+	let resolver = Arc::new(DnsResolver::new()?);
 
 	tokio::spawn(serve_unsecure(
 		http_listener,
 		client.clone(),
 		dynamic_config.clone(),
+		resolver.clone(),
 	));
 
 	tokio::spawn(serve_secure(
@@ -52,6 +49,7 @@ pub async fn run() -> Result<()> {
 		client,
 		dynamic_config.clone(),
 		server_config.clone(),
+		resolver,
 	));
 
 	println!("Server started");
@@ -67,20 +65,29 @@ pub async fn run() -> Result<()> {
 }
 
 async fn serve_unsecure(
-	listener: TcpListener,
+	listener: Listener,
 	client: Client<HttpConnector, Incoming>,
 	dynamic_config: Arc<RwLock<DynamicConfig>>,
+	resolver: Arc<DnsResolver>,
 ) {
 	loop {
-		if let Ok((stream, _)) = listener.accept().await {
+		if let Ok((stream, client_addr)) = listener.accept().await {
+			let Ok(server_addr) = stream.local_addr() else {
+				continue;
+			};
+
 			let client = client.clone();
 			let dynamic_config = dynamic_config.clone();
+			let resolver = resolver.clone();
 
 			tokio::spawn(async move {
 				let io = TokioIo::new(stream);
 
 				auto::Builder::new(TokioExecutor::new())
-					.serve_connection_with_upgrades(io, Proxy::new_unsecure(client, dynamic_config))
+					.serve_connection_with_upgrades(
+						io,
+						Proxy::new_unsecure(client, dynamic_config, resolver, client_addr, server_addr),
+					)
 					.await
 			});
 		}
@@ -88,25 +95,36 @@ async fn serve_unsecure(
 }
 
 async fn serve_secure(
-	listener: TcpListener,
+	listener: Listener,
 	client: Client<HttpConnector, Incoming>,
 	dynamic_config: Arc<RwLock<DynamicConfig>>,
 	server_config: Arc<RwLock<ServerConfig>>,
+	resolver: Arc<DnsResolver>,
 ) {
 	loop {
-		if let Ok((stream, _)) = listener.accept().await {
+		if let Ok((stream, client_addr)) = listener.accept().await {
+			let Ok(server_addr) = stream.local_addr() else {
+				continue;
+			};
+
 			let client = client.clone();
 			let dynamic_config = dynamic_config.clone();
 			let server_config = Arc::new(server_config.read().unwrap().internal.clone());
+			let resolver = resolver.clone();
 
 			tokio::spawn(async move {
 				let tls_acceptor = tokio_rustls::TlsAcceptor::from(server_config);
 
+				// Capture the pre-TLS peer address: backends should see the real
+				// client, not yxorp's own TLS session.
 				let tls_stream = tls_acceptor.accept(stream).await?;
 				let io = TokioIo::new(tls_stream);
 
 				auto::Builder::new(TokioExecutor::new())
-					.serve_connection_with_upgrades(io, Proxy::new_secure(client, dynamic_config))
+					.serve_connection_with_upgrades(
+						io,
+						Proxy::new_secure(client, dynamic_config, resolver, client_addr, server_addr),
+					)
 					.await
 			});
 		}